@@ -0,0 +1,396 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+
+/// Approximate in-memory size of a cached value, used by
+/// [`WeightedLruCache`] to bound total memory rather than just entry count.
+pub trait Weight {
+    fn weight(&self) -> u64;
+}
+
+struct CacheState<K, V> {
+    ready: HashMap<K, V>,
+    // Least-recently-used key is at the front.
+    order: VecDeque<K>,
+    total_weight: u64,
+    pending: HashMap<K, Arc<Notify>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone + Weight> CacheState<K, V> {
+    fn touch(&mut self, key: &K) -> Option<V> {
+        let value = self.ready.get(key)?.clone();
+
+        self.order.retain(|k| k != key);
+        self.order.push_back(key.clone());
+
+        Some(value)
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(value) = self.ready.remove(key) {
+            self.total_weight -= value.weight();
+        }
+
+        self.order.retain(|k| k != key);
+    }
+
+    fn insert(&mut self, key: K, value: V, max_entries: usize, max_weight: u64) {
+        self.remove(&key);
+
+        self.total_weight += value.weight();
+        self.order.push_back(key.clone());
+        self.ready.insert(key, value);
+
+        while self.ready.len() > max_entries || self.total_weight > max_weight {
+            let Some(lru_key) = self.order.pop_front() else {
+                break;
+            };
+
+            self.remove(&lru_key);
+        }
+    }
+}
+
+/// Releases a leader's `pending` entry and wakes any followers waiting on it,
+/// even if the leader's download future is dropped before finishing (e.g. a
+/// losing [`crate::solution::SolutionFuture`] child). Call [`Self::disarm`]
+/// once the happy path has already done this same cleanup itself, so a
+/// normal completion doesn't notify twice.
+struct PendingGuard<'a, K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone + Weight,
+{
+    cache: &'a WeightedLruCache<K, V>,
+    key: K,
+    disarmed: bool,
+}
+
+impl<K, V> PendingGuard<'_, K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone + Weight,
+{
+    fn disarm(mut self) {
+        self.disarmed = true;
+    }
+}
+
+impl<K, V> Drop for PendingGuard<'_, K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone + Weight,
+{
+    fn drop(&mut self) {
+        if self.disarmed {
+            return;
+        }
+
+        let mut state = self.cache.state.lock().unwrap();
+
+        if let Some(notify) = state.pending.remove(&self.key) {
+            notify.notify_waiters();
+        }
+    }
+}
+
+/// A download memo cache bounded by both entry count and total [`Weight`],
+/// evicting least-recently-used entries once either limit is exceeded. Only
+/// successful downloads are cached; concurrent [`Self::get_or_download`]
+/// calls for the same key share a single in-flight download rather than each
+/// issuing their own.
+pub struct WeightedLruCache<K, V> {
+    max_entries: usize,
+    max_weight: u64,
+    state: Mutex<CacheState<K, V>>,
+}
+
+impl<K, V> WeightedLruCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone + Weight,
+{
+    pub fn new(max_entries: usize, max_weight: u64) -> Self {
+        WeightedLruCache {
+            max_entries,
+            max_weight,
+            state: Mutex::new(CacheState {
+                ready: HashMap::new(),
+                order: VecDeque::new(),
+                total_weight: 0,
+                pending: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Returns the cached value for `key`, calling `download` to populate it
+    /// on a miss. A failed `download` is not cached, so the next caller
+    /// (whether this one retrying or a fresh one) will try again.
+    pub async fn get_or_download<E, Fut, F>(&self, key: K, download: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        loop {
+            let mut notified = {
+                let mut state = self.state.lock().unwrap();
+
+                if let Some(value) = state.touch(&key) {
+                    return Ok(value);
+                }
+
+                let notify = match state.pending.get(&key) {
+                    Some(notify) => notify.clone(),
+                    None => {
+                        state.pending.insert(key.clone(), Arc::new(Notify::new()));
+                        break;
+                    }
+                };
+
+                // Built while the lock is still held: a `Notified` is only
+                // guaranteed to see a `notify_waiters()` call that happens
+                // *after* it was constructed, so building it here ensures the
+                // leader's `notify_waiters()` landing right after this lock
+                // is released can't be missed.
+                Box::pin(notify.notified_owned())
+            };
+
+            // Someone else is already downloading this key; wait for them to
+            // finish and loop back around to check the cache again.
+            notified.as_mut().await;
+        }
+
+        // Guards against this future being dropped before `download`
+        // finishes (e.g. this caller is a `SolutionFuture` child that lost
+        // the race): without it, the pending entry and its `Notify` would be
+        // stranded, and every future caller for this key would block on a
+        // `Notify` that nothing will ever fire.
+        let guard = PendingGuard {
+            cache: self,
+            key: key.clone(),
+            disarmed: false,
+        };
+
+        let result = download().await;
+
+        let mut state = self.state.lock().unwrap();
+        let notify = state
+            .pending
+            .remove(&key)
+            .expect("this caller registered the pending entry before downloading");
+
+        if let Ok(value) = &result {
+            state.insert(key, value.clone(), self.max_entries, self.max_weight);
+        }
+
+        notify.notify_waiters();
+        guard.disarm();
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Sized(&'static str, u64);
+
+    impl Weight for Sized {
+        fn weight(&self) -> u64 {
+            self.1
+        }
+    }
+
+    #[tokio::test]
+    async fn should_download_on_miss_and_cache_on_hit() {
+        let cache: WeightedLruCache<&str, Sized> = WeightedLruCache::new(10, 1000);
+        let downloads = AtomicUsize::new(0);
+
+        let download = || async {
+            downloads.fetch_add(1, Ordering::SeqCst);
+            Result::<_, &str>::Ok(Sized("a", 1))
+        };
+
+        assert_eq!(cache.get_or_download("a", download).await, Ok(Sized("a", 1)));
+        assert_eq!(cache.get_or_download("a", download).await, Ok(Sized("a", 1)));
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn should_not_cache_failed_downloads() {
+        let cache: WeightedLruCache<&str, Sized> = WeightedLruCache::new(10, 1000);
+        let downloads = AtomicUsize::new(0);
+
+        let result = cache
+            .get_or_download("a", || async {
+                downloads.fetch_add(1, Ordering::SeqCst);
+                Result::<Sized, &str>::Err("boom")
+            })
+            .await;
+
+        assert_eq!(result, Err("boom"));
+
+        let result = cache
+            .get_or_download("a", || async {
+                downloads.fetch_add(1, Ordering::SeqCst);
+                Result::<Sized, &str>::Ok(Sized("a", 1))
+            })
+            .await;
+
+        assert_eq!(result, Ok(Sized("a", 1)));
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn should_evict_least_recently_used_entry_past_max_entries() {
+        let cache: WeightedLruCache<&str, Sized> = WeightedLruCache::new(2, 1000);
+
+        cache
+            .get_or_download("a", || async { Result::<_, &str>::Ok(Sized("a", 1)) })
+            .await
+            .unwrap();
+        cache
+            .get_or_download("b", || async { Result::<_, &str>::Ok(Sized("b", 1)) })
+            .await
+            .unwrap();
+
+        // touch "a" so "b" becomes the least recently used entry
+        cache
+            .get_or_download("a", || async { unreachable!("should be cached") as Result<_, &str> })
+            .await
+            .unwrap();
+
+        cache
+            .get_or_download("c", || async { Result::<_, &str>::Ok(Sized("c", 1)) })
+            .await
+            .unwrap();
+
+        let downloads = AtomicUsize::new(0);
+
+        cache
+            .get_or_download("b", || async {
+                downloads.fetch_add(1, Ordering::SeqCst);
+                Result::<_, &str>::Ok(Sized("b", 1))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(downloads.load(Ordering::SeqCst), 1, "b should have been evicted");
+    }
+
+    #[tokio::test]
+    async fn should_evict_entries_past_max_weight() {
+        let cache: WeightedLruCache<&str, Sized> = WeightedLruCache::new(10, 10);
+
+        cache
+            .get_or_download("a", || async { Result::<_, &str>::Ok(Sized("a", 6)) })
+            .await
+            .unwrap();
+        cache
+            .get_or_download("b", || async { Result::<_, &str>::Ok(Sized("b", 6)) })
+            .await
+            .unwrap();
+
+        let downloads = AtomicUsize::new(0);
+
+        cache
+            .get_or_download("a", || async {
+                downloads.fetch_add(1, Ordering::SeqCst);
+                Result::<_, &str>::Ok(Sized("a", 6))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            downloads.load(Ordering::SeqCst),
+            1,
+            "a should have been evicted once total weight exceeded the 10-byte budget"
+        );
+    }
+
+    // Uses a multi-threaded runtime so the leader and follower can genuinely
+    // race across OS threads, which is what exercises the lost-wakeup window
+    // between the follower releasing the state lock and registering as a
+    // `Notify` waiter.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn should_share_a_single_in_flight_download_across_concurrent_callers() {
+        let cache: Arc<WeightedLruCache<&str, Sized>> = Arc::new(WeightedLruCache::new(10, 1000));
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let started = Arc::new(tokio::sync::Notify::new());
+
+        let leader = {
+            let cache = cache.clone();
+            let downloads = downloads.clone();
+            let started = started.clone();
+
+            tokio::spawn(async move {
+                cache
+                    .get_or_download("a", || async move {
+                        downloads.fetch_add(1, Ordering::SeqCst);
+                        started.notify_one();
+                        tokio::task::yield_now().await;
+                        Result::<_, &str>::Ok(Sized("a", 1))
+                    })
+                    .await
+            })
+        };
+
+        started.notified().await;
+
+        let follower = {
+            let cache = cache.clone();
+            tokio::spawn(async move {
+                cache
+                    .get_or_download("a", || async {
+                        unreachable!("leader already downloading") as Result<_, &str>
+                    })
+                    .await
+            })
+        };
+
+        assert_eq!(leader.await.unwrap(), Ok(Sized("a", 1)));
+        assert_eq!(follower.await.unwrap(), Ok(Sized("a", 1)));
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn should_let_a_follower_promote_to_leader_after_the_leader_is_dropped_mid_download() {
+        let cache: Arc<WeightedLruCache<&str, Sized>> = Arc::new(WeightedLruCache::new(10, 1000));
+        let started = Arc::new(tokio::sync::Notify::new());
+
+        let leader = {
+            let cache = cache.clone();
+            let started = started.clone();
+
+            tokio::spawn(async move {
+                cache
+                    .get_or_download("a", || async move {
+                        started.notify_one();
+                        // stands in for a `SolutionFuture` child that never
+                        // gets to finish because a sibling repo won the race
+                        std::future::pending::<Result<Sized, &str>>().await
+                    })
+                    .await
+            })
+        };
+
+        started.notified().await;
+        leader.abort();
+        let _ = leader.await;
+
+        // without the pending entry being released on drop, this would
+        // block forever on a `Notify` the aborted leader will never fire
+        let result = cache
+            .get_or_download("a", || async { Result::<_, &str>::Ok(Sized("a", 1)) })
+            .await;
+
+        assert_eq!(result, Ok(Sized("a", 1)));
+    }
+}
@@ -0,0 +1,40 @@
+use tokio::sync::watch;
+
+/// Snapshot of where a single repository stands in a
+/// [`crate::solution::Solution0`] race.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepoStatus<E> {
+    /// The first attempt has not finished yet.
+    Pending,
+    /// A previous attempt failed and this is about to be retried.
+    Retrying { attempt: usize },
+    /// Every attempt failed and this repository is out of the race.
+    Failed(E),
+    /// The download finished successfully.
+    Succeeded,
+}
+
+/// Read-only handle to the latest [`RepoStatus`] snapshot for every
+/// repository in a race, backed by a [`watch::Receiver`] so the current state
+/// is always readable without blocking. Closes once the race resolves and its
+/// underlying sender is dropped.
+pub struct ProgressWatch<E> {
+    receiver: watch::Receiver<Vec<RepoStatus<E>>>,
+}
+
+impl<E: Clone> ProgressWatch<E> {
+    pub(crate) fn new(receiver: watch::Receiver<Vec<RepoStatus<E>>>) -> Self {
+        ProgressWatch { receiver }
+    }
+
+    /// Returns the latest snapshot without blocking.
+    pub fn snapshot(&self) -> Vec<RepoStatus<E>> {
+        self.receiver.borrow().clone()
+    }
+
+    /// Waits until the snapshot changes, or fails once the race has resolved
+    /// and the sending half has been dropped.
+    pub async fn changed(&mut self) -> Result<(), watch::error::RecvError> {
+        self.receiver.changed().await
+    }
+}
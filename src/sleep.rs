@@ -0,0 +1,159 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use tokio::sync::Notify;
+
+/// Abstracts "wait for a duration" and "what time is it" so that code like
+/// [`crate::retry::Retryer`] can sleep on a real clock in production while
+/// tests drive a virtual one instead of sleeping in wall-clock time.
+#[async_trait]
+pub trait SleepProvider {
+    async fn sleep(&self, dur: Duration);
+
+    fn now(&self) -> Instant;
+}
+
+/// Default, production [`SleepProvider`] backed by the Tokio runtime clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioSleepProvider;
+
+#[async_trait]
+impl SleepProvider for TokioSleepProvider {
+    async fn sleep(&self, dur: Duration) {
+        if dur.is_zero() {
+            return;
+        }
+
+        tokio::time::sleep(dur).await;
+    }
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+struct MockClock {
+    // `Instant` has no stable way to construct an arbitrary point in time,
+    // so the virtual clock is tracked as an offset from a real anchor taken
+    // once at construction.
+    start: Instant,
+    elapsed: Mutex<Duration>,
+    waiters: Mutex<Vec<(Duration, Arc<Notify>)>>,
+}
+
+/// A [`SleepProvider`] whose clock only moves when [`MockSleepProvider::advance`]
+/// is called, letting tests assert exact backoff timing and ordering without
+/// depending on wall-clock time.
+#[derive(Clone)]
+pub struct MockSleepProvider {
+    clock: Arc<MockClock>,
+}
+
+impl MockSleepProvider {
+    pub fn new() -> Self {
+        MockSleepProvider {
+            clock: Arc::new(MockClock {
+                start: Instant::now(),
+                elapsed: Mutex::new(Duration::ZERO),
+                waiters: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Moves the virtual clock forward by `dur`, waking any sleepers whose
+    /// deadline has now been reached.
+    pub fn advance(&self, dur: Duration) {
+        let now = {
+            let mut elapsed = self.clock.elapsed.lock().unwrap();
+            *elapsed += dur;
+            *elapsed
+        };
+
+        self.clock
+            .waiters
+            .lock()
+            .unwrap()
+            .retain(|(deadline, notify)| {
+                if *deadline <= now {
+                    notify.notify_waiters();
+                    false
+                } else {
+                    true
+                }
+            });
+    }
+}
+
+impl Default for MockSleepProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl SleepProvider for MockSleepProvider {
+    async fn sleep(&self, dur: Duration) {
+        if dur.is_zero() {
+            return;
+        }
+
+        let deadline = *self.clock.elapsed.lock().unwrap() + dur;
+        let notify = Arc::new(Notify::new());
+        let mut notified = Box::pin(notify.clone().notified_owned());
+
+        // `notified` is built above, before this waiter is pushed: a
+        // `Notified` is only guaranteed to see a `notify_waiters()` call that
+        // happens *after* it was constructed, so building it first ensures an
+        // `advance()` racing in right after this lock is released can't be
+        // missed.
+        {
+            let mut waiters = self.clock.waiters.lock().unwrap();
+            waiters.push((deadline, notify));
+        }
+
+        while *self.clock.elapsed.lock().unwrap() < deadline {
+            notified.as_mut().await;
+        }
+    }
+
+    fn now(&self) -> Instant {
+        self.clock.start + *self.clock.elapsed.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn should_not_resolve_sleep_until_advanced_past_deadline() {
+        let provider = MockSleepProvider::new();
+        let start = provider.now();
+
+        let sleeper = tokio::spawn({
+            let provider = provider.clone();
+            async move {
+                provider.sleep(Duration::from_millis(300)).await;
+            }
+        });
+
+        // give the spawned task a chance to register its wait
+        tokio::task::yield_now().await;
+
+        provider.advance(Duration::from_millis(200));
+        assert!(!sleeper.is_finished());
+
+        provider.advance(Duration::from_millis(100));
+        sleeper.await.unwrap();
+
+        assert_eq!(provider.now(), start + Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn should_return_immediately_for_a_zero_duration() {
+        let provider = MockSleepProvider::new();
+
+        provider.sleep(Duration::ZERO).await;
+    }
+}
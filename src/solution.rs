@@ -1,12 +1,20 @@
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::future::Future;
+use std::hash::Hash;
 use std::pin::Pin;
-use std::task::Poll;
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
 
 use async_trait::async_trait;
+use tokio::sync::watch;
+use tokio::time::Sleep;
 
+use crate::cache::{Weight, WeightedLruCache};
+use crate::progress::{ProgressWatch, RepoStatus};
 use crate::retry::Retryer;
+use crate::sleep::{SleepProvider, TokioSleepProvider};
 use crate::statement::*;
 
 #[async_trait]
@@ -17,32 +25,210 @@ pub trait Solution<T, E, D: Download<T, E> + Send + Sync> {
 pub struct Solution0;
 
 #[async_trait]
-impl<T: Debug, E: Debug, D: Download<T, E> + Send + Sync + 'static + Clone> Solution<T, E, D>
-    for Solution0
+impl<T: Debug, E: Debug + Clone + Send + Sync, D: Download<T, E> + Send + Sync + 'static + Clone>
+    Solution<T, E, D> for Solution0
 {
     async fn solve(repositories: Vec<D>) -> Option<T> {
+        Self::solve_with(repositories, TokioSleepProvider).await
+    }
+}
+
+impl Solution0 {
+    /// Same as [`Solution::solve`], but lets the caller swap in a
+    /// [`SleepProvider`] (e.g. a virtual clock in tests) that each download
+    /// waits its ticks on. The retryer here keeps a zero backoff, so no
+    /// delay is waited out between retries.
+    pub async fn solve_with<T, E, D, S>(repositories: Vec<D>, sleep_provider: S) -> Option<T>
+    where
+        T: Debug,
+        E: Debug + Clone,
+        D: Download<T, E> + Send + Sync + 'static + Clone,
+        S: SleepProvider + Sync + Send + Clone,
+    {
         if repositories.is_empty() {
             return None;
         }
 
-        let retryer = Retryer::new(3);
+        let retryer = Retryer::new(3).with_sleep_provider(sleep_provider.clone());
 
         let futures: Vec<_> = repositories
             .iter()
             .map(|repo| {
+                let sleep_provider = sleep_provider.clone();
+
                 Box::pin(retryer.retry(move || {
                     // I'm not happy with cloning here, but we need to pass ownership from closure
                     // to async block multiple times
                     let repo = repo.clone();
+                    let sleep_provider = sleep_provider.clone();
 
-                    async move { repo.download().await }
+                    async move { repo.download(&sleep_provider).await }
                 }))
             })
             .collect();
 
         let future = SolutionFuture::new(futures);
 
-        future.await.ok()
+        future.await.and_then(|result| result.ok())
+    }
+
+    /// Same as [`Self::solve_with`], but looks up each repository in `cache`
+    /// before downloading, and stores newly downloaded values back into it.
+    /// Repositories double as cache keys, so concurrent calls for the same
+    /// repository share one download instead of each racing their own.
+    pub async fn solve_cached<T, E, D, S>(
+        repositories: Vec<D>,
+        sleep_provider: S,
+        cache: Arc<WeightedLruCache<D, T>>,
+    ) -> Option<T>
+    where
+        T: Debug + Clone + Weight,
+        E: Debug + Clone,
+        D: Download<T, E> + Send + Sync + 'static + Clone + Eq + Hash,
+        S: SleepProvider + Sync + Send + Clone,
+    {
+        if repositories.is_empty() {
+            return None;
+        }
+
+        let retryer = Retryer::new(3).with_sleep_provider(sleep_provider.clone());
+
+        let futures: Vec<_> = repositories
+            .into_iter()
+            .map(|repo| {
+                let cache = cache.clone();
+                let sleep_provider = sleep_provider.clone();
+
+                Box::pin(retryer.retry(move || {
+                    let repo = repo.clone();
+                    let cache = cache.clone();
+                    let sleep_provider = sleep_provider.clone();
+
+                    async move {
+                        cache
+                            .get_or_download(repo.clone(), || async move { repo.download(&sleep_provider).await })
+                            .await
+                    }
+                }))
+            })
+            .collect();
+
+        let future = SolutionFuture::new(futures);
+
+        future.await.and_then(|result| result.ok())
+    }
+
+    /// Same as [`Self::solve_with`], but gives up after `deadline` if no
+    /// repository has succeeded by then, dropping the remaining in-flight
+    /// downloads instead of waiting for them to finish.
+    pub async fn solve_with_deadline<T, E, D>(repositories: Vec<D>, deadline: Duration) -> Option<T>
+    where
+        T: Debug,
+        E: Debug + Clone,
+        D: Download<T, E> + Send + Sync + 'static + Clone,
+    {
+        if repositories.is_empty() {
+            return None;
+        }
+
+        let retryer = Retryer::new(3);
+
+        let futures: Vec<_> = repositories
+            .iter()
+            .map(|repo| {
+                Box::pin(retryer.retry(move || {
+                    let repo = repo.clone();
+
+                    async move { repo.download(&TokioSleepProvider).await }
+                }))
+            })
+            .collect();
+
+        let future = SolutionFuture::with_deadline(futures, deadline);
+
+        future.await.and_then(|result| result.ok())
+    }
+
+    /// Same as [`Self::solve_with`], but also returns a [`ProgressWatch`]
+    /// reporting each repository's [`RepoStatus`] as the race unfolds, for a
+    /// UI or logger to observe without blocking the race itself. The watch
+    /// closes once the returned future resolves.
+    pub fn solve_with_progress<T, E, D, S>(
+        repositories: Vec<D>,
+        sleep_provider: S,
+    ) -> (impl Future<Output = Option<T>>, ProgressWatch<E>)
+    where
+        T: Debug,
+        E: Debug + Clone,
+        D: Download<T, E> + Send + Sync + 'static + Clone,
+        S: SleepProvider + Sync + Send + Clone,
+    {
+        let (tx, rx) = watch::channel(vec![RepoStatus::Pending; repositories.len()]);
+        let progress = ProgressWatch::new(rx);
+
+        let future = async move {
+            if repositories.is_empty() {
+                return None;
+            }
+
+            let retryer = Retryer::new(3).with_sleep_provider(sleep_provider.clone());
+
+            let futures: Vec<_> = repositories
+                .iter()
+                .enumerate()
+                .map(|(index, repo)| {
+                    let tx = tx.clone();
+                    let mut attempt = 0;
+                    let sleep_provider = sleep_provider.clone();
+
+                    Box::pin(retryer.retry(move || {
+                        let repo = repo.clone();
+                        let sleep_provider = sleep_provider.clone();
+
+                        // The first call is the initial attempt, already
+                        // reflected by the `Pending` status it starts at;
+                        // only calls after that are actual retries.
+                        if attempt > 0 {
+                            tx.send_modify(|statuses| {
+                                statuses[index] = RepoStatus::Retrying { attempt };
+                            });
+                        }
+                        attempt += 1;
+
+                        async move { repo.download(&sleep_provider).await }
+                    }))
+                })
+                .collect();
+
+            let future = SolutionFuture::with_progress(futures, tx);
+
+            future.await.and_then(|result| result.ok())
+        };
+
+        (future, progress)
+    }
+}
+
+/// Wakes [`SolutionFuture`] when the child future at `index` makes progress,
+/// by recording `index` in the shared ready queue before forwarding the
+/// wakeup to whichever waker last polled the parent.
+struct ChildWaker {
+    index: usize,
+    ready_queue: Arc<Mutex<VecDeque<usize>>>,
+    parent_waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl Wake for ChildWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready_queue.lock().unwrap().push_back(self.index);
+
+        if let Some(waker) = self.parent_waker.lock().unwrap().as_ref() {
+            waker.wake_by_ref();
+        }
     }
 }
 
@@ -54,12 +240,23 @@ where
     futures: Vec<Pin<Box<F>>>,
     is_ready_future: Vec<bool>,
     pending_count: usize,
+    child_wakers: Vec<Arc<ChildWaker>>,
+    ready_queue: Arc<Mutex<VecDeque<usize>>>,
+    parent_waker: Arc<Mutex<Option<Waker>>>,
+    // Polled alongside the children so an overall deadline participates in
+    // the same waker machinery instead of wrapping this future externally.
+    deadline: Option<Pin<Box<Sleep>>>,
+    // Updated with each child's terminal status as it resolves; retry
+    // attempts in between are reported by the retrying future itself, since
+    // that's the only place that knows about them.
+    status: Option<watch::Sender<Vec<RepoStatus<E>>>>,
 }
 
 impl<F, T, E> SolutionFuture<F, T, E>
 where
     F: Future<Output = Result<T, E>>,
     F::Output: Debug,
+    E: Clone,
 {
     fn new(futures: Vec<Pin<Box<F>>>) -> Self {
         assert!(
@@ -67,15 +264,54 @@ where
             "Futures vec to await should not be empty"
         );
 
+        let len = futures.len();
+        // Seed the queue with every index so the first poll drives each
+        // child at least once, the same way a freshly spawned task would.
+        let ready_queue = Arc::new(Mutex::new((0..len).collect::<VecDeque<_>>()));
+        let parent_waker = Arc::new(Mutex::new(None));
+
+        let child_wakers = (0..len)
+            .map(|index| {
+                Arc::new(ChildWaker {
+                    index,
+                    ready_queue: ready_queue.clone(),
+                    parent_waker: parent_waker.clone(),
+                })
+            })
+            .collect();
+
         // it would be better to use bit arithmetic and store integers here
         // to minimize memory footprint
         // but I hope it is not critical for test task
-        let is_ready_future = vec![false; futures.len()];
+        let is_ready_future = vec![false; len];
 
         SolutionFuture {
-            pending_count: futures.len(),
+            pending_count: len,
             futures,
             is_ready_future,
+            child_wakers,
+            ready_queue,
+            parent_waker,
+            deadline: None,
+            status: None,
+        }
+    }
+
+    /// Same as [`Self::new`], but resolves to `None` once `deadline` elapses
+    /// if no child has succeeded by then, dropping the rest on the spot.
+    fn with_deadline(futures: Vec<Pin<Box<F>>>, deadline: Duration) -> Self {
+        SolutionFuture {
+            deadline: Some(Box::pin(tokio::time::sleep(deadline))),
+            ..Self::new(futures)
+        }
+    }
+
+    /// Same as [`Self::new`], but reports each child's terminal
+    /// [`RepoStatus`] to `status` as it resolves.
+    fn with_progress(futures: Vec<Pin<Box<F>>>, status: watch::Sender<Vec<RepoStatus<E>>>) -> Self {
+        SolutionFuture {
+            status: Some(status),
+            ..Self::new(futures)
         }
     }
 }
@@ -84,49 +320,70 @@ impl<F, T, E> Future for SolutionFuture<F, T, E>
 where
     F: Future<Output = Result<T, E>>,
     F::Output: Debug,
+    E: Clone,
 {
-    type Output = Result<T, E>;
+    type Output = Option<Result<T, E>>;
 
-    fn poll(
-        mut self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Self::Output> {
-        let mut last_error = None;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        *self.parent_waker.lock().unwrap() = Some(cx.waker().clone());
 
-        println!("Check in {:?}", Instant::now());
+        let mut last_error = None;
 
         let SolutionFuture {
             futures,
             is_ready_future,
             pending_count,
+            child_wakers,
+            ready_queue,
+            deadline,
+            status,
+            ..
         } = &mut *self;
 
-        for (future, is_ready) in futures.iter_mut().zip(is_ready_future.iter_mut()) {
-            if *is_ready {
-                continue;
+        if let Some(deadline) = deadline.as_mut() {
+            if Future::poll(deadline.as_mut(), cx).is_ready() {
+                return Poll::Ready(None);
             }
+        }
 
-            if let Poll::Ready(result) = Future::poll(future.as_mut(), cx) {
-                println!("{result:?}");
+        // Drain whatever woke us up since the last poll; indices pushed by a
+        // child waking itself *during* this pass land back on the queue and
+        // are only handled on the next wakeup, not re-polled here.
+        let woken: Vec<usize> = ready_queue.lock().unwrap().drain(..).collect();
 
-                *is_ready = true;
+        for index in woken {
+            if is_ready_future[index] {
+                continue;
+            }
+
+            let waker = Waker::from(child_wakers[index].clone());
+            let mut child_cx = Context::from_waker(&waker);
 
+            if let Poll::Ready(result) = Future::poll(futures[index].as_mut(), &mut child_cx) {
+                is_ready_future[index] = true;
                 *pending_count -= 1;
 
+                if let Some(status) = status.as_ref() {
+                    let repo_status = match &result {
+                        Ok(_) => RepoStatus::Succeeded,
+                        Err(err) => RepoStatus::Failed(err.clone()),
+                    };
+
+                    status.send_modify(|statuses| statuses[index] = repo_status);
+                }
+
                 if result.is_ok() {
-                    return Poll::Ready(result);
+                    return Poll::Ready(Some(result));
                 }
 
                 last_error = Some(result);
             }
         }
 
-        println!("________");
-
-        if self.pending_count == 0 {
+        if *pending_count == 0 {
             // last_error should be initialized anyway in case we haven't succeeded
             if let Some(last_error) = last_error {
-                return Poll::Ready(last_error);
+                return Poll::Ready(Some(last_error));
             }
         }
 
@@ -136,12 +393,17 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
     use async_trait::async_trait;
     use tokio::time;
 
+    use crate::progress::RepoStatus;
+    use crate::sleep::{MockSleepProvider, SleepProvider, TokioSleepProvider};
     use crate::statement::Download;
 
-    use super::{Solution, Solution0};
+    use super::{Solution, Solution0, SolutionFuture};
 
     #[derive(Clone)]
     struct MockRepo {
@@ -153,11 +415,9 @@ mod tests {
 
     #[async_trait]
     impl Download<String, String> for MockRepo {
-        async fn download(self) -> Result<String, String> {
-            let mut interval = time::interval(time::Duration::from_millis(1));
-
+        async fn download(self, sleep_provider: &(dyn SleepProvider + Send + Sync)) -> Result<String, String> {
             for _i in 0..self.tick_times {
-                interval.tick().await;
+                sleep_provider.sleep(time::Duration::from_millis(1)).await;
             }
 
             if self.panic {
@@ -294,4 +554,186 @@ mod tests {
 
         assert_eq!(Solution0::solve(repos).await, None);
     }
+
+    #[tokio::test]
+    async fn should_drive_download_ticks_from_a_virtual_clock() {
+        let sleep_provider = MockSleepProvider::new();
+        let repos = vec![MockRepo {
+            failing: false,
+            panic: false,
+            tick_times: 3,
+            value: "1".to_owned(),
+        }];
+
+        let solving = tokio::spawn(Solution0::solve_with(repos, sleep_provider.clone()));
+
+        // each tick only elapses once the virtual clock is advanced past it,
+        // so the download can't finish ahead of these 3 advances no matter
+        // how fast this test actually runs
+        for _ in 0..3 {
+            tokio::task::yield_now().await;
+            assert!(!solving.is_finished());
+            sleep_provider.advance(time::Duration::from_millis(1));
+        }
+
+        assert_eq!(solving.await.unwrap(), Some("1".to_owned()));
+    }
+
+    /// Wraps a future and counts how many times it gets polled, so tests can
+    /// assert on `SolutionFuture`'s own polling behavior rather than just its
+    /// end result.
+    struct CountingFuture<Fut> {
+        inner: Fut,
+        polls: Arc<AtomicUsize>,
+    }
+
+    impl<Fut: std::future::Future> std::future::Future for CountingFuture<Fut> {
+        type Output = Fut::Output;
+
+        fn poll(
+            self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Self::Output> {
+            self.polls.fetch_add(1, Ordering::SeqCst);
+
+            // Safety: `inner` is never moved out of `self`.
+            let inner = unsafe { self.map_unchecked_mut(|this| &mut this.inner) };
+
+            inner.poll(cx)
+        }
+    }
+
+    #[tokio::test]
+    async fn should_poll_each_child_a_bounded_number_of_times() {
+        let repo_count = 200;
+        let tick_times = 5;
+
+        let poll_counts: Vec<_> = (0..repo_count)
+            .map(|_| Arc::new(AtomicUsize::new(0)))
+            .collect();
+
+        let futures: Vec<_> = poll_counts
+            .iter()
+            .cloned()
+            .map(|polls| {
+                Box::pin(CountingFuture {
+                    polls,
+                    inner: async move {
+                        let mut interval = time::interval(time::Duration::from_millis(1));
+
+                        for _ in 0..tick_times {
+                            interval.tick().await;
+                        }
+
+                        Result::<&str, &str>::Err("never succeeds")
+                    },
+                })
+            })
+            .collect();
+
+        let result = SolutionFuture::new(futures).await;
+
+        assert_eq!(result, Some(Err("never succeeds")));
+
+        for polls in poll_counts {
+            let polls = polls.load(Ordering::SeqCst);
+
+            // Each repo should only be polled roughly once per tick of its
+            // own timer (plus the initial poll), regardless of how many of
+            // the other `repo_count` repos happened to wake up in between.
+            assert!(
+                polls <= tick_times + 20,
+                "expected at most {} polls, got {polls}",
+                tick_times + 20
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn should_return_none_when_deadline_elapses_before_any_repo_succeeds() {
+        let repos = vec![MockRepo {
+            failing: false,
+            panic: false,
+            tick_times: 100,
+            value: "1".to_owned(),
+        }];
+
+        assert_eq!(
+            Solution0::solve_with_deadline(repos, time::Duration::from_millis(10)).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn should_succeed_when_a_repo_finishes_before_the_deadline() {
+        let repos = vec![MockRepo {
+            failing: false,
+            panic: false,
+            tick_times: 3,
+            value: "1".to_owned(),
+        }];
+
+        assert_eq!(
+            Solution0::solve_with_deadline(repos, time::Duration::from_secs(10)).await,
+            Some("1".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn should_report_succeeded_status_for_the_winning_repo() {
+        let repos = vec![MockRepo {
+            failing: false,
+            panic: false,
+            tick_times: 3,
+            value: "1".to_owned(),
+        }];
+
+        let (future, mut progress) =
+            Solution0::solve_with_progress(repos, TokioSleepProvider);
+
+        assert_eq!(progress.snapshot(), vec![RepoStatus::Pending]);
+        assert_eq!(future.await, Some("1".to_owned()));
+
+        while progress.changed().await.is_ok() {}
+
+        assert_eq!(progress.snapshot(), vec![RepoStatus::Succeeded]);
+    }
+
+    #[tokio::test]
+    async fn should_report_failed_status_when_every_repo_fails() {
+        let repos = vec![MockRepo {
+            failing: true,
+            panic: false,
+            tick_times: 1,
+            value: "1".to_owned(),
+        }];
+
+        let (future, mut progress) =
+            Solution0::solve_with_progress(repos, TokioSleepProvider);
+
+        assert_eq!(future.await, None);
+
+        // drain every update until the channel closes, then check the final
+        // snapshot rather than assuming which poll the update lands on
+        while progress.changed().await.is_ok() {}
+
+        assert_eq!(progress.snapshot(), vec![RepoStatus::Failed("1".to_owned())]);
+    }
+
+    #[tokio::test]
+    async fn should_close_the_progress_channel_once_the_race_resolves() {
+        let repos = vec![MockRepo {
+            failing: false,
+            panic: false,
+            tick_times: 1,
+            value: "1".to_owned(),
+        }];
+
+        let (future, mut progress) =
+            Solution0::solve_with_progress(repos, TokioSleepProvider);
+
+        assert_eq!(future.await, Some("1".to_owned()));
+
+        while progress.changed().await.is_ok() {}
+    }
 }
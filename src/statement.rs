@@ -1,44 +1,89 @@
-use std::time::Instant;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use derive_more::Display;
 use thiserror::Error;
-use tokio::time;
 
-#[derive(Error, Debug)]
+use crate::cache::Weight;
+use crate::sleep::SleepProvider;
+
+#[derive(Error, Debug, Clone)]
 pub enum ServerError {
     #[error("Server {0:?}: abruptly disconnected")]
     Disconnected(ServerName),
+    #[error("Server {0:?}: returned a malformed binary")]
+    MalformedBinary(ServerName),
+}
+
+/// Classifies a [`ServerError`] for use as a [`crate::retry::RetryPolicy`],
+/// e.g. `Retryer::new(3).with_policy(classify)`. `Disconnected` is treated as
+/// transient and retried; `MalformedBinary` is permanent, since retrying
+/// against the same bad response would just fail the same way, so it stops
+/// retrying immediately.
+pub fn classify(err: &ServerError, _attempt: usize) -> bool {
+    match err {
+        ServerError::Disconnected(_) => true,
+        ServerError::MalformedBinary(_) => false,
+    }
 }
 
-#[derive(Display, Debug)]
-#[display(fmt = "Binary[source='{}']", "from.0")]
+#[derive(Display, Debug, Clone)]
+#[display(fmt = "Binary[source='{}', size={size_bytes}]", "from.0")]
 pub struct Binary {
     #[allow(dead_code)]
     from: ServerName,
+    size_bytes: usize,
 }
 
-#[derive(Debug, Clone)]
+impl Weight for Binary {
+    fn weight(&self) -> u64 {
+        self.size_bytes as u64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ServerName(pub String);
 
 #[async_trait]
 pub trait Download<T, E> {
-    async fn download(self) -> Result<T, E>;
+    /// `sleep_provider` lets callers swap in a virtual clock (e.g. in tests)
+    /// so the delay between ticks doesn't have to be wall-clock time.
+    async fn download(self, sleep_provider: &(dyn SleepProvider + Send + Sync)) -> Result<T, E>;
 }
 
 #[async_trait]
 impl Download<Binary, ServerError> for ServerName {
-    async fn download(self) -> Result<Binary, ServerError> {
-        let mut interval = time::interval(time::Duration::from_millis(100));
-        println!("Start download {:?} at {:?}", self, Instant::now());
+    async fn download(self, sleep_provider: &(dyn SleepProvider + Send + Sync)) -> Result<Binary, ServerError> {
+        let tick = Duration::from_millis(100);
+
         for _i in 0..5 {
-            interval.tick().await;
+            sleep_provider.sleep(tick).await;
 
-            println!("Tick download {:?} at {:?}", self, Instant::now());
             if rand::random::<f32>() < 0.1 {
                 return Err(ServerError::Disconnected(self));
             }
         }
-        Ok(Binary { from: self })
+
+        let size_bytes = rand::random::<u16>() as usize + 1;
+
+        Ok(Binary {
+            from: self,
+            size_bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_retry_a_disconnected_error() {
+        assert!(classify(&ServerError::Disconnected(ServerName("a".into())), 0));
+    }
+
+    #[test]
+    fn should_not_retry_a_malformed_binary_error() {
+        assert!(!classify(&ServerError::MalformedBinary(ServerName("a".into())), 0));
     }
 }